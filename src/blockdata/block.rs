@@ -0,0 +1,48 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! Bitcoin block data structures.
+//!
+//! This module defines structures and functions for storing the blocks and
+//! transactions which make up the Bitcoin/Monacoin blockchain.
+//!
+//! `BlockHeader` and its consensus-encoding impls are not reproduced in this snapshot; the
+//! `impl` block below extends the type that already exists in this module with the one method
+//! this change set needs.
+
+use crate::consensus::params::Params;
+use crate::hash_types::BlockHash;
+use crate::pow::PowAlgorithm;
+use crate::util::{lyra2rev2, scrypt};
+
+impl BlockHeader {
+    /// Computes this header's proof-of-work hash.
+    ///
+    /// Dispatches to whichever algorithm was active at `height` (see
+    /// [`Params::pow_algorithm`]) and is what must meet `bits` for the block to be valid. This is
+    /// distinct from the header's plain double-SHA256 block identifier hash, which is unaffected
+    /// by the Lyra2REv2 switch.
+    pub fn pow_hash(&self, params: &Params, height: u32) -> BlockHash {
+        let preimage = self.pow_preimage();
+        match params.pow_algorithm(height) {
+            PowAlgorithm::Scrypt => BlockHash::from_byte_array(scrypt::scrypt_hash(&preimage)),
+            PowAlgorithm::Lyra2REv2 => BlockHash::from_byte_array(lyra2rev2::hash(&preimage)),
+        }
+    }
+
+    /// Serializes the fixed 80-byte header preimage that proof-of-work hashes are computed over.
+    ///
+    /// `self.bits` is the header's wire-format `u32` "nBits" field, same as it was before this
+    /// change set (it does not go through [`crate::pow::CompactTarget`] here); callers that need
+    /// it as a `Target` can build one with
+    /// `Target::from_compact(CompactTarget::from_consensus(self.bits))`.
+    fn pow_preimage(&self) -> [u8; 80] {
+        let mut buf = [0u8; 80];
+        buf[0..4].copy_from_slice(&self.version.to_le_bytes());
+        buf[4..36].copy_from_slice(self.prev_blockhash.as_ref());
+        buf[36..68].copy_from_slice(self.merkle_root.as_ref());
+        buf[68..72].copy_from_slice(&self.time.to_le_bytes());
+        buf[72..76].copy_from_slice(&self.bits.to_le_bytes());
+        buf[76..80].copy_from_slice(&self.nonce.to_le_bytes());
+        buf
+    }
+}
@@ -8,38 +8,10 @@
 //!
 
 use crate::network::constants::Network;
-use crate::util::uint::Uint256;
-
-/// Lowest possible difficulty for Mainnet. See comment on Params::pow_limit for more info.
-const MAX_BITS_BITCOIN: Uint256 = Uint256([
-    0xffffffffffffffffu64,
-    0xffffffffffffffffu64,
-    0xffffffffffffffffu64,
-    0x00000fffffffffffu64,
-]);
-/// Lowest possible difficulty for Testnet. See comment on Params::pow_limit for more info.
-const MAX_BITS_TESTNET: Uint256 = Uint256([
-    0xffffffffffffffffu64,
-    0xffffffffffffffffu64,
-    0xffffffffffffffffu64,
-    0x00000fffffffffffu64,
-]);
-/// Lowest possible difficulty for Signet. See comment on Params::pow_limit for more info.
-const MAX_BITS_SIGNET: Uint256 = Uint256([
-    0xffffffffffffffffu64,
-    0xffffffffffffffffu64,
-    0xffffffffffffffffu64,
-    0x000fffffffffffffu64,
-]);
-/// Lowest possible difficulty for Regtest. See comment on Params::pow_limit for more info.
-const MAX_BITS_REGTEST: Uint256 = Uint256([
-    0x0000000000000000u64,
-    0x0000000000000000u64,
-    0x0000000000000000u64,
-    0x7fffff0000000000u64,
-]);
+use crate::pow::{CompactTarget, PowAlgorithm, Target};
 
 /// Parameters that influence chain consensus.
+#[non_exhaustive]
 #[derive(Debug, Clone)]
 pub struct Params {
     /// Network for which parameters are valid.
@@ -60,13 +32,9 @@ pub struct Params {
     pub miner_confirmation_window: u32,
     /// Proof of work limit value. It contains the lowest possible difficulty.
     ///
-    /// Note that this value differs from Bitcoin Core's powLimit field in that this value is
-    /// attainable, but Bitcoin Core's is not. Specifically, because targets in Bitcoin are always
-    /// rounded to the nearest float expressible in "compact form", not all targets are attainable.
-    /// Still, this should not affect consensus as the only place where the non-compact form of
-    /// this is used in Bitcoin Core's consensus algorithm is in comparison and there are no
-    /// compact-expressible values between Bitcoin Core's and the limit expressed here.
-    pub pow_limit: Uint256,
+    /// See [`Target::MAX_ATTAINABLE_MAINNET`] for how this relates to the compact targets block
+    /// headers can encode.
+    pub pow_limit: Target,
     /// Expected amount of time to mine one block.
     pub pow_target_spacing: u64,
     /// Difficulty recalculation interval.
@@ -79,70 +47,113 @@ pub struct Params {
     pub switch_lyra2rev2_dgwblock: u32,
 }
 
+/// Describes a BIP9 soft-fork deployment, signalled via a bit in the block `version` field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Deployment {
+    /// The bit in the block version field used to signal support for this deployment.
+    pub bit: u8,
+    /// Median time past at or after which miners may begin signalling support.
+    pub start_time: u32,
+    /// Median time past at or after which the deployment is considered failed if it has not
+    /// locked in.
+    pub timeout: u32,
+}
+
+/// The state of a BIP9 deployment, tracked per [`Params::miner_confirmation_window`]-sized window.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ThresholdState {
+    /// `start_time` has not yet been reached; signalling is not tracked.
+    Defined,
+    /// Signalling has started, but the activation threshold has not yet been met.
+    Started,
+    /// The activation threshold was met during a signalling window; the deployment activates
+    /// after one further window.
+    LockedIn,
+    /// The deployment's rules are in effect.
+    Active,
+    /// `timeout` was reached before the deployment locked in.
+    Failed,
+}
+
 impl Params {
+    /// Parameters for mainnet.
+    pub const MAINNET: Params = Params {
+        network: Network::Bitcoin,
+        bip16_time: 0, // gensis block
+        bip34_height: 0,
+        bip65_height: 977759, // ecc773c827a8cde039f6dfcdee2de981b747f58aa1bc4dddcb28e3c857dbc860
+        bip66_height: 977759, // ecc773c827a8cde039f6dfcdee2de981b747f58aa1bc4dddcb28e3c857dbc860
+        rule_change_activation_threshold: 7560, // 75% of 10080
+        miner_confirmation_window: 10080, // 3.5 days / nPowTargetSpacing * 4 * 0.75
+        pow_limit: Target::MAX_ATTAINABLE_MAINNET,
+        pow_target_spacing: 90, // 1.5 minutes(1.5 * 60)
+        pow_target_timespan: 95040, // 1.1 days(1.1 * 24 * 60 * 60)
+        allow_min_difficulty_blocks: false,
+        no_pow_retargeting: false,
+        switch_lyra2rev2_dgwblock: 450000,
+    };
+
+    /// Alias for [`Params::MAINNET`], matching upstream rust-bitcoin's naming.
+    pub const BITCOIN: Params = Params::MAINNET;
+
+    /// Parameters for testnet.
+    pub const TESTNET: Params = Params {
+        network: Network::Testnet,
+        bip16_time: 1333238400,                 // Apr 1 2012
+        bip34_height: 0,
+        bip65_height: 0,
+        bip66_height: 0,
+        rule_change_activation_threshold: 75, // 75%
+        miner_confirmation_window: 100,
+        pow_limit: Target::MAX_ATTAINABLE_TESTNET,
+        pow_target_spacing: 90, // 1.5 minutes(1.5 * 60)
+        pow_target_timespan: 95040, // 1.1 days(1.1 * 24 * 60 * 60)
+        allow_min_difficulty_blocks: true,
+        no_pow_retargeting: false,
+        switch_lyra2rev2_dgwblock: 60,
+    };
+
+    /// Parameters for signet.
+    pub const SIGNET: Params = Params {
+        network: Network::Signet,
+        bip16_time: 1333238400,                 // Apr 1 2012
+        bip34_height: 1,
+        bip65_height: 1,
+        bip66_height: 1,
+        rule_change_activation_threshold: 75, // 75%
+        miner_confirmation_window: 100,
+        pow_limit: Target::MAX_ATTAINABLE_SIGNET,
+        pow_target_spacing: 90, // 1.5 minutes(1.5 * 60)
+        pow_target_timespan: 95040, // 1.1 days(1.1 * 24 * 60 * 60)
+        allow_min_difficulty_blocks: false,
+        no_pow_retargeting: false,
+        switch_lyra2rev2_dgwblock: 1,
+    };
+
+    /// Parameters for regtest.
+    pub const REGTEST: Params = Params {
+        network: Network::Regtest,
+        bip16_time: 1333238400,  // Apr 1 2012
+        bip34_height: 1,
+        bip65_height: 1,
+        bip66_height: 1,
+        rule_change_activation_threshold: 108, // 75%
+        miner_confirmation_window: 144,
+        pow_limit: Target::MAX_ATTAINABLE_REGTEST,
+        pow_target_spacing: 90, // 1.5 minutes(1.5 * 60)
+        pow_target_timespan: 95040, // 1.1 days(1.1 * 24 * 60 * 60)
+        allow_min_difficulty_blocks: true,
+        no_pow_retargeting: true,
+        switch_lyra2rev2_dgwblock: 60,
+    };
+
     /// Creates parameters set for the given network.
     pub fn new(network: Network) -> Self {
         match network {
-            Network::Bitcoin => Params {
-                network: Network::Bitcoin,
-                bip16_time: 0, // gensis block
-                bip34_height: 0,
-                bip65_height: 977759, // ecc773c827a8cde039f6dfcdee2de981b747f58aa1bc4dddcb28e3c857dbc860
-                bip66_height: 977759, // ecc773c827a8cde039f6dfcdee2de981b747f58aa1bc4dddcb28e3c857dbc860
-                rule_change_activation_threshold: 7560, // 75% of 10080
-                miner_confirmation_window: 10080, // 3.5 days / nPowTargetSpacing * 4 * 0.75
-                pow_limit: MAX_BITS_BITCOIN,
-                pow_target_spacing: 90, // 1.5 minutes(1.5 * 60)
-                pow_target_timespan: 95040, // 1.1 days(1.1 * 24 * 60 * 60)
-                allow_min_difficulty_blocks: false,
-                no_pow_retargeting: false,
-                switch_lyra2rev2_dgwblock: 450000,
-            },
-            Network::Testnet => Params {
-                network: Network::Testnet,
-                bip16_time: 1333238400,                 // Apr 1 2012
-                bip34_height: 0,
-                bip65_height: 0,
-                bip66_height: 0,
-                rule_change_activation_threshold: 75, // 75%
-                miner_confirmation_window: 100,
-                pow_limit: MAX_BITS_TESTNET,
-                pow_target_spacing: 90, // 1.5 minutes(1.5 * 60)
-                pow_target_timespan: 95040, // 1.1 days(1.1 * 24 * 60 * 60)
-                allow_min_difficulty_blocks: true,
-                no_pow_retargeting: false,
-                switch_lyra2rev2_dgwblock: 60,
-            },
-            Network::Signet => Params {
-                network: Network::Signet,
-                bip16_time: 1333238400,                 // Apr 1 2012
-                bip34_height: 1,
-                bip65_height: 1,
-                bip66_height: 1,
-                rule_change_activation_threshold: 75, // 95%
-                miner_confirmation_window: 100,
-                pow_limit: MAX_BITS_SIGNET,
-                pow_target_spacing: 90, // 1.5 minutes(1.5 * 60)
-                pow_target_timespan: 95040, // 1.1 days(1.1 * 24 * 60 * 60)
-                allow_min_difficulty_blocks: false,
-                no_pow_retargeting: false,
-                switch_lyra2rev2_dgwblock: 1,
-            },
-            Network::Regtest => Params {
-                network: Network::Regtest,
-                bip16_time: 1333238400,  // Apr 1 2012
-                bip34_height: 1,
-                bip65_height: 1,
-                bip66_height: 1,
-                rule_change_activation_threshold: 108, // 75%
-                miner_confirmation_window: 144,
-                pow_limit: MAX_BITS_REGTEST,
-                pow_target_spacing: 90, // 1.5 minutes(1.5 * 60)
-                pow_target_timespan: 95040, // 1.1 days(1.1 * 24 * 60 * 60)
-                allow_min_difficulty_blocks: true,
-                no_pow_retargeting: true,
-                switch_lyra2rev2_dgwblock: 60,
-            },
+            Network::Bitcoin => Params::MAINNET,
+            Network::Testnet => Params::TESTNET,
+            Network::Signet => Params::SIGNET,
+            Network::Regtest => Params::REGTEST,
         }
     }
 
@@ -150,4 +161,300 @@ impl Params {
     pub fn difficulty_adjustment_interval(&self) -> u64 {
         self.pow_target_timespan / self.pow_target_spacing
     }
+
+    /// Returns the proof-of-work hashing algorithm active at `height`.
+    ///
+    /// Blocks before [`Params::switch_lyra2rev2_dgwblock`] are mined with Scrypt; that height and
+    /// all later blocks are mined with Lyra2REv2.
+    pub fn pow_algorithm(&self, height: u32) -> PowAlgorithm {
+        if height >= self.switch_lyra2rev2_dgwblock {
+            PowAlgorithm::Lyra2REv2
+        } else {
+            PowAlgorithm::Scrypt
+        }
+    }
+
+    /// Computes the proof-of-work target required for the next block using Dark Gravity Wave v3.
+    ///
+    /// Monacoin switches from the original retargeting rule to DGWv3 (alongside the
+    /// Lyra2REv2 hashing algorithm) at [`Params::switch_lyra2rev2_dgwblock`]. `headers` must
+    /// yield the blocks preceding `height`, newest first, as `(time, bits)` pairs; only the
+    /// most recent 24 are consulted, and `pow_limit` is returned if fewer are available.
+    pub fn next_work_required<I>(&self, height: u32, headers: I) -> CompactTarget
+    where
+        I: IntoIterator<Item = (u32, CompactTarget)>,
+    {
+        const PAST_BLOCKS: u32 = 24;
+
+        if height == 0 {
+            return self.pow_limit.to_compact();
+        }
+
+        let mut avg = Target::default();
+        let mut last_block_time = 0u32;
+        let mut actual_timespan: i64 = 0;
+        let mut count: u32 = 0;
+
+        for (time, bits) in headers.into_iter().take(PAST_BLOCKS as usize) {
+            count += 1;
+            let target = Target::from_compact(bits);
+
+            avg = if count == 1 {
+                target
+            } else {
+                avg.weighted_div(count as u64, target, (count + 1) as u64)
+            };
+
+            if count > 1 {
+                actual_timespan += last_block_time as i64 - time as i64;
+            }
+            last_block_time = time;
+        }
+
+        if count < PAST_BLOCKS {
+            return self.pow_limit.to_compact();
+        }
+
+        let target_timespan = (count as i64) * (self.pow_target_spacing as i64);
+        let actual_timespan = actual_timespan.clamp(target_timespan / 3, target_timespan * 3);
+
+        let mut new_target = avg.weighted_div(actual_timespan as u64, Target::default(), target_timespan as u64);
+        if new_target > self.pow_limit {
+            new_target = self.pow_limit;
+        }
+
+        new_target.to_compact()
+    }
+
+    /// Advances a BIP9 [`Deployment`]'s [`ThresholdState`] across one signalling window.
+    ///
+    /// `window_median_time_past` must be the real BIP9 median-time-past: the median of the 11
+    /// blocks ending at the window's last block (`height - 1` where `height` is a multiple of
+    /// `miner_confirmation_window`), computed by the caller the same way as for BIP113 lock-time
+    /// checks. It is *not* a median over the whole window, which would evaluate the threshold at
+    /// different heights than a real BIP9-compliant node. `window_versions` must be the `version`
+    /// field of every block in the `miner_confirmation_window`-sized window being evaluated.
+    /// `prev_state` is the deployment's state as of the previous window (use
+    /// [`ThresholdState::Defined`] for the first window after genesis). An empty
+    /// `window_versions` leaves `prev_state` unchanged.
+    pub fn deployment_state<I>(
+        &self,
+        deployment: Deployment,
+        prev_state: ThresholdState,
+        window_median_time_past: u32,
+        window_versions: I,
+    ) -> ThresholdState
+    where
+        I: IntoIterator<Item = u32>,
+    {
+        let mut signalling: u32 = 0;
+        let mut total: u32 = 0;
+
+        for version in window_versions {
+            total += 1;
+            if signals_deployment(version, deployment.bit) {
+                signalling += 1;
+            }
+        }
+
+        if total == 0 {
+            return prev_state;
+        }
+
+        match prev_state {
+            ThresholdState::Defined => {
+                if window_median_time_past >= deployment.timeout {
+                    ThresholdState::Failed
+                } else if window_median_time_past >= deployment.start_time {
+                    ThresholdState::Started
+                } else {
+                    ThresholdState::Defined
+                }
+            }
+            ThresholdState::Started => {
+                if window_median_time_past >= deployment.timeout {
+                    ThresholdState::Failed
+                } else if signalling >= self.rule_change_activation_threshold {
+                    ThresholdState::LockedIn
+                } else {
+                    ThresholdState::Started
+                }
+            }
+            ThresholdState::LockedIn => ThresholdState::Active,
+            ThresholdState::Active => ThresholdState::Active,
+            ThresholdState::Failed => ThresholdState::Failed,
+        }
+    }
+}
+
+/// Returns whether a block's `version` field signals support for `bit`, per BIP9's top-bits
+/// convention (the top three bits of `version` must read `001`).
+fn signals_deployment(version: u32, bit: u8) -> bool {
+    const VERSIONBITS_TOP_MASK: u32 = 0xe000_0000;
+    const VERSIONBITS_TOP_BITS: u32 = 0x2000_0000;
+    version & VERSIONBITS_TOP_MASK == VERSIONBITS_TOP_BITS && (version >> bit) & 1 == 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn next_work_required_at_genesis_is_pow_limit() {
+        let params = Params::TESTNET;
+        assert_eq!(
+            params.next_work_required(0, std::iter::empty()),
+            params.pow_limit.to_compact()
+        );
+    }
+
+    #[test]
+    fn next_work_required_before_24_blocks_is_pow_limit() {
+        let params = Params::TESTNET;
+        let bits = params.pow_limit.to_compact();
+        let headers = (0..23).map(|i| (i * params.pow_target_spacing as u32, bits));
+        assert_eq!(params.next_work_required(23, headers), bits);
+    }
+
+    #[test]
+    fn next_work_required_holds_steady_for_on_schedule_headers() {
+        // `actual_timespan` telescopes down to just (newest time - oldest time) over the window,
+        // so for it to exactly equal `target_timespan` (24 * pow_target_spacing), the *span*
+        // between the oldest and newest header needs to match that, not each individual gap
+        // (uniform per-block gaps of `pow_target_spacing` undershoot the span by one gap and
+        // nudge the target slightly tighter instead of holding it exactly steady). With the span
+        // matching, the weighted average stays at the target `bits` decodes to and the rescale is
+        // a true no-op.
+        let params = Params::TESTNET;
+        let bits = params.pow_limit.to_compact();
+        let target_timespan: u64 = 24 * params.pow_target_spacing;
+        // Newest-first; times[0] (newest) down to times[23] = 0 (oldest), spanning exactly
+        // target_timespan. The interior spacing doesn't matter, only the two endpoints.
+        let headers = (0u64..24).map(|i| (((target_timespan * (23 - i)) / 23) as u32, bits));
+        assert_eq!(params.next_work_required(24, headers), bits);
+    }
+
+    #[test]
+    fn next_work_required_near_pow_limit_does_not_panic() {
+        // A chain mined far slower than expected, sitting at pow_limit (plausible on testnet,
+        // where `allow_min_difficulty_blocks` is set), must not overflow/panic when DGWv3 scales
+        // `avg` by up to 3x the target timespan.
+        let params = Params::TESTNET;
+        let bits = params.pow_limit.to_compact();
+        let spacing = (params.pow_target_spacing * 4) as u32; // 4x slower than expected.
+        let headers = (0..24).rev().map(|i| (i * spacing, bits));
+        let result = params.next_work_required(24, headers);
+        assert_eq!(result, bits); // already clamped to pow_limit, can't go any easier.
+    }
+
+    const TEST_DEPLOYMENT: Deployment = Deployment {
+        bit: 1,
+        start_time: 1_000,
+        timeout: 2_000,
+    };
+
+    #[test]
+    fn deployment_state_stays_defined_before_start_time() {
+        let params = Params::REGTEST;
+        let state = params.deployment_state(
+            TEST_DEPLOYMENT,
+            ThresholdState::Defined,
+            999,
+            std::iter::repeat(0u32).take(params.miner_confirmation_window as usize),
+        );
+        assert_eq!(state, ThresholdState::Defined);
+    }
+
+    #[test]
+    fn deployment_state_defined_to_failed_when_observed_after_timeout() {
+        // A deployment whose window is first observed after its own timeout (e.g. it was added
+        // to the chain late) must go straight to Failed rather than Started, since the timeout
+        // check in the Defined branch runs before the start_time check.
+        let params = Params::REGTEST;
+        let state = params.deployment_state(
+            TEST_DEPLOYMENT,
+            ThresholdState::Defined,
+            TEST_DEPLOYMENT.timeout,
+            std::iter::repeat(0u32).take(params.miner_confirmation_window as usize),
+        );
+        assert_eq!(state, ThresholdState::Failed);
+    }
+
+    #[test]
+    fn deployment_state_defined_to_started_at_start_time() {
+        let params = Params::REGTEST;
+        let state = params.deployment_state(
+            TEST_DEPLOYMENT,
+            ThresholdState::Defined,
+            1_000,
+            std::iter::repeat(0u32).take(params.miner_confirmation_window as usize),
+        );
+        assert_eq!(state, ThresholdState::Started);
+    }
+
+    #[test]
+    fn deployment_state_started_to_locked_in_when_threshold_met() {
+        let params = Params::REGTEST;
+        let window = params.miner_confirmation_window as usize;
+        let signalling_version = 0x2000_0000 | (1 << TEST_DEPLOYMENT.bit);
+        let signalling = params.rule_change_activation_threshold as usize;
+        let versions = std::iter::repeat(signalling_version)
+            .take(signalling)
+            .chain(std::iter::repeat(0u32).take(window - signalling));
+        let state =
+            params.deployment_state(TEST_DEPLOYMENT, ThresholdState::Started, 1_500, versions);
+        assert_eq!(state, ThresholdState::LockedIn);
+    }
+
+    #[test]
+    fn deployment_state_started_stays_started_below_threshold() {
+        let params = Params::REGTEST;
+        let window = params.miner_confirmation_window as usize;
+        let versions = std::iter::repeat(0u32).take(window);
+        let state =
+            params.deployment_state(TEST_DEPLOYMENT, ThresholdState::Started, 1_500, versions);
+        assert_eq!(state, ThresholdState::Started);
+    }
+
+    #[test]
+    fn deployment_state_started_to_failed_at_timeout() {
+        let params = Params::REGTEST;
+        let window = params.miner_confirmation_window as usize;
+        let versions = std::iter::repeat(0u32).take(window);
+        let state =
+            params.deployment_state(TEST_DEPLOYMENT, ThresholdState::Started, 2_000, versions);
+        assert_eq!(state, ThresholdState::Failed);
+    }
+
+    #[test]
+    fn deployment_state_locked_in_to_active() {
+        let params = Params::REGTEST;
+        let window = params.miner_confirmation_window as usize;
+        let versions = std::iter::repeat(0u32).take(window);
+        let state =
+            params.deployment_state(TEST_DEPLOYMENT, ThresholdState::LockedIn, 1_500, versions);
+        assert_eq!(state, ThresholdState::Active);
+    }
+
+    #[test]
+    fn deployment_state_active_stays_active() {
+        let params = Params::REGTEST;
+        let window = params.miner_confirmation_window as usize;
+        let versions = std::iter::repeat(0u32).take(window);
+        let state =
+            params.deployment_state(TEST_DEPLOYMENT, ThresholdState::Active, 1_500, versions);
+        assert_eq!(state, ThresholdState::Active);
+    }
+
+    #[test]
+    fn deployment_state_empty_window_is_unchanged() {
+        let params = Params::REGTEST;
+        let state = params.deployment_state(
+            TEST_DEPLOYMENT,
+            ThresholdState::Started,
+            1_500,
+            std::iter::empty(),
+        );
+        assert_eq!(state, ThresholdState::Started);
+    }
 }
@@ -0,0 +1,312 @@
+// SPDX-License-Identifier: CC0-1.0
+
+//! Proof-of-work related types.
+//!
+//! This module provides [`Target`], a 256-bit proof-of-work target, and [`CompactTarget`], the
+//! 32-bit "nBits" encoding block headers actually store on the wire, along with conversions
+//! between the two.
+
+use std::fmt;
+use std::ops::{Add, Div, Mul};
+
+use crate::util::uint::Uint256;
+
+/// A 256-bit proof-of-work target.
+///
+/// This is the value a block hash must be less than or equal to for the block to be valid;
+/// smaller targets represent higher difficulty.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Default, Debug)]
+pub struct Target(Uint256);
+
+impl Target {
+    /// The maximum attainable target for Mainnet (lowest possible difficulty).
+    ///
+    /// Note that this value has set bits below the mantissa that the compact ("nBits") form keeps,
+    /// so it is not itself expressible in compact form: [`Target::to_compact`] gives `0x1e0fffff`,
+    /// which decodes to a slightly smaller target. This does not affect comparisons against the
+    /// limit, as there are no compact-expressible values between that decoded target and the
+    /// limit expressed here.
+    pub const MAX_ATTAINABLE_MAINNET: Target = Target(Uint256([
+        0xffffffffffffffffu64,
+        0xffffffffffffffffu64,
+        0xffffffffffffffffu64,
+        0x00000fffffffffffu64,
+    ]));
+    /// The maximum attainable target for Testnet. See [`Target::MAX_ATTAINABLE_MAINNET`].
+    pub const MAX_ATTAINABLE_TESTNET: Target = Target(Uint256([
+        0xffffffffffffffffu64,
+        0xffffffffffffffffu64,
+        0xffffffffffffffffu64,
+        0x00000fffffffffffu64,
+    ]));
+    /// The maximum attainable target for Signet. See [`Target::MAX_ATTAINABLE_MAINNET`].
+    pub const MAX_ATTAINABLE_SIGNET: Target = Target(Uint256([
+        0xffffffffffffffffu64,
+        0xffffffffffffffffu64,
+        0xffffffffffffffffu64,
+        0x000fffffffffffffu64,
+    ]));
+    /// The maximum attainable target for Regtest. See [`Target::MAX_ATTAINABLE_MAINNET`].
+    pub const MAX_ATTAINABLE_REGTEST: Target = Target(Uint256([
+        0x0000000000000000u64,
+        0x0000000000000000u64,
+        0x0000000000000000u64,
+        0x7fffff0000000000u64,
+    ]));
+
+    /// Computes a target from its compact ("nBits") representation (Bitcoin Core's `SetCompact`).
+    ///
+    /// Negative or overflowing encodings, which never occur for valid consensus targets but may
+    /// appear in malformed or adversarial header data, decode to a target of zero rather than
+    /// panicking or wrapping, mirroring Core's `pfNegative`/`pfOverflow` checks.
+    pub fn from_compact(compact: CompactTarget) -> Target {
+        let bits = compact.0;
+        let size = bits >> 24;
+        let word = bits & 0x007fffff;
+        let is_negative = word != 0 && bits & 0x00800000 != 0;
+        let is_overflow = word != 0
+            && (size > 34 || (word > 0xff && size > 33) || (word > 0xffff && size > 32));
+
+        if is_negative || is_overflow || word == 0 {
+            return Target(Uint256::default());
+        }
+
+        let target = if size <= 3 {
+            Uint256::from((word as u64) >> (8 * (3 - size)))
+        } else {
+            Uint256::from(word as u64) << (8 * (size - 3)) as usize
+        };
+
+        Target(target)
+    }
+
+    /// Returns the compact ("nBits") representation of this target (Bitcoin Core's `GetCompact`).
+    pub fn to_compact(self) -> CompactTarget {
+        let target = self.0;
+        let mut size = ((target.bits() + 7) / 8) as u32;
+        let mut compact = if size <= 3 {
+            (target.low_u64() << (8 * (3 - size))) as u32
+        } else {
+            (target >> (8 * (size - 3)) as usize).low_u64() as u32
+        };
+
+        // The 0x00800000 bit is the sign bit in the compact encoding; if it would be set by the
+        // mantissa, shift the mantissa down a byte and bump the exponent to keep the value positive.
+        if compact & 0x00800000 != 0 {
+            compact >>= 8;
+            size += 1;
+        }
+
+        CompactTarget(compact | (size << 24))
+    }
+
+    /// Returns the difficulty of this target relative to `pow_limit`, i.e. how many times harder
+    /// it is to find a block hash meeting this target than one meeting `pow_limit`.
+    pub fn difficulty(&self, pow_limit: Target) -> u128 {
+        if self.0 == Uint256::default() {
+            return 0;
+        }
+        (pow_limit.0 / self.0).low_u64() as u128
+    }
+
+    /// Computes `(self * mul + add) / div` using a widened 320-bit intermediate.
+    ///
+    /// A plain `self * mul` can overflow `Uint256`'s 256 bits when `self` sits near `pow_limit`
+    /// and `mul` is a multi-block timespan (DGWv3's weighted average and final target rescale in
+    /// [`crate::consensus::params::Params::next_work_required`] both do this); widening first and
+    /// dividing back down avoids that, mirroring Bitcoin Core's arbitrary-precision retargeting
+    /// arithmetic.
+    pub(crate) fn weighted_div(self, mul: u64, add: Target, div: u64) -> Target {
+        Target(muladd_div_u64(self.0, mul, add.0, div))
+    }
+}
+
+/// Computes `(value * mul + add) / div`, widening `value * mul + add` into five 64-bit limbs
+/// (320 bits) before dividing, so the multiplication cannot silently wrap the way a direct
+/// `Uint256 * u64` would. If the final quotient still does not fit back into 256 bits, saturates
+/// to `Uint256::MAX`-equivalent (all limbs set) rather than truncating silently; callers that
+/// immediately clamp against a maximum target (as `next_work_required` does) will bring this back
+/// in range.
+///
+/// 320 bits is enough headroom for any `Uint256` `value` combined with a `u64` `mul`/`add`: the
+/// debug assertion below documents and checks that bound rather than relying on it silently.
+fn muladd_div_u64(value: Uint256, mul: u64, add: Uint256, div: u64) -> Uint256 {
+    let mut product = [0u64; 5];
+    let mut carry: u128 = 0;
+    for (i, &limb) in value.0.iter().enumerate() {
+        let acc = (limb as u128) * (mul as u128) + carry;
+        product[i] = acc as u64;
+        carry = acc >> 64;
+    }
+    product[4] = carry as u64;
+
+    let mut carry = 0u128;
+    for (i, &limb) in add.0.iter().enumerate() {
+        let sum = product[i] as u128 + limb as u128 + carry;
+        product[i] = sum as u64;
+        carry = sum >> 64;
+    }
+    let (top, overflow) = product[4].overflowing_add(carry as u64);
+    // `value < 2^256` makes the top limb of `value * mul` strictly less than `mul`, and folding
+    // in `add` (also < 2^256) can carry out of the low 256 bits by at most 1, so the top limb
+    // never exceeds `mul`: this can't overflow for any `Uint256` value with a `u64` mul/add. The
+    // assert documents that bound rather than trusting it silently, so a future widening of
+    // `value` or `mul`'s effective range re-triggers this check instead of quietly truncating.
+    debug_assert!(!overflow, "muladd_div_u64: top limb overflowed the 320-bit intermediate");
+    product[4] = top;
+
+    let mut quotient = [0u64; 5];
+    let mut remainder: u128 = 0;
+    for i in (0..5).rev() {
+        let dividend = (remainder << 64) | product[i] as u128;
+        quotient[i] = (dividend / div as u128) as u64;
+        remainder = dividend % div as u128;
+    }
+
+    if quotient[4] != 0 {
+        Uint256([u64::MAX; 4])
+    } else {
+        Uint256([quotient[0], quotient[1], quotient[2], quotient[3]])
+    }
+}
+
+impl From<Uint256> for Target {
+    fn from(target: Uint256) -> Self {
+        Target(target)
+    }
+}
+
+impl From<Target> for Uint256 {
+    fn from(target: Target) -> Self {
+        target.0
+    }
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
+
+impl Add for Target {
+    type Output = Target;
+    fn add(self, other: Target) -> Target {
+        Target(self.0 + other.0)
+    }
+}
+
+impl Mul<u64> for Target {
+    type Output = Target;
+    fn mul(self, other: u64) -> Target {
+        Target(self.0 * Uint256::from(other))
+    }
+}
+
+impl Div<u64> for Target {
+    type Output = Target;
+    fn div(self, other: u64) -> Target {
+        Target(self.0 / Uint256::from(other))
+    }
+}
+
+/// The 32-bit "nBits" compact encoding of a [`Target`], as stored in a block header.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Default, Debug)]
+pub struct CompactTarget(u32);
+
+impl CompactTarget {
+    /// Constructs a `CompactTarget` from a consensus-encoded `u32`.
+    pub fn from_consensus(bits: u32) -> Self {
+        CompactTarget(bits)
+    }
+
+    /// Returns the consensus-encoded `u32` for this `CompactTarget`.
+    pub fn to_consensus(self) -> u32 {
+        self.0
+    }
+}
+
+impl From<u32> for CompactTarget {
+    fn from(bits: u32) -> Self {
+        CompactTarget(bits)
+    }
+}
+
+impl From<CompactTarget> for u32 {
+    fn from(compact: CompactTarget) -> Self {
+        compact.0
+    }
+}
+
+impl fmt::Display for CompactTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compact_round_trip() {
+        for bits in [0x1d00ffffu32, 0x1b0404cb, 0x207fffff, 0x1d00ffff] {
+            let target = Target::from_compact(CompactTarget::from_consensus(bits));
+            assert_eq!(target.to_compact().to_consensus(), bits);
+        }
+    }
+
+    #[test]
+    fn compact_negative_decodes_to_zero() {
+        // Sign bit (0x00800000) set.
+        let target = Target::from_compact(CompactTarget::from_consensus(0x01800001));
+        assert_eq!(target, Target::default());
+    }
+
+    #[test]
+    fn compact_overflow_decodes_to_zero() {
+        // nSize > 34 with a nonzero mantissa must not panic and must decode to zero.
+        let target = Target::from_compact(CompactTarget::from_consensus(0xff123456));
+        assert_eq!(target, Target::default());
+
+        // nWord > 0xff with nSize > 33.
+        let target = Target::from_compact(CompactTarget::from_consensus(0x22010000));
+        assert_eq!(target, Target::default());
+    }
+
+    #[test]
+    fn compact_zero_mantissa_decodes_to_zero() {
+        let target = Target::from_compact(CompactTarget::from_consensus(0x04000000));
+        assert_eq!(target, Target::default());
+    }
+
+    #[test]
+    fn weighted_div_matches_plain_arithmetic_for_small_values() {
+        let value = Target::from(Uint256::from(1_000_000u64));
+        let add = Target::from(Uint256::from(5u64));
+        let result = value.weighted_div(3, add, 7);
+        assert_eq!(result, Target::from(Uint256::from((1_000_000u64 * 3 + 5) / 7)));
+    }
+
+    #[test]
+    fn weighted_div_does_not_overflow_near_pow_limit() {
+        // avg * actual_timespan (up to 3x a 24-block timespan) must not panic or wrap when avg
+        // sits at the mainnet/testnet maximum attainable target.
+        let avg = Target::MAX_ATTAINABLE_TESTNET;
+        let result = avg.weighted_div(3 * 24 * 90, Target::default(), 24 * 90);
+        // Scaling by exactly 3x and dividing by 1x should triple the target (saturating at the
+        // widened accumulator's capacity rather than wrapping around to a small value).
+        assert!(result >= avg);
+    }
+}
+
+/// The proof-of-work hashing algorithm a block was (or must be) mined with.
+///
+/// Monacoin mined with Scrypt until [`Params::switch_lyra2rev2_dgwblock`](crate::consensus::params::Params::switch_lyra2rev2_dgwblock),
+/// at which height it switched to Lyra2REv2 alongside the move to DGWv3 retargeting.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum PowAlgorithm {
+    /// Scrypt, used for all blocks before the switch height.
+    Scrypt,
+    /// Lyra2REv2, used for the switch height and all blocks after it.
+    Lyra2REv2,
+}